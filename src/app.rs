@@ -5,11 +5,10 @@ use glutin::{
     context::{ContextApi, ContextAttributesBuilder, NotCurrentContext},
     display::{Display, GetGlDisplay},
     prelude::*,
-    surface::{GlSurface, SwapInterval},
 };
 use glutin_winit::DisplayBuilder;
 use raw_window_handle::HasRawWindowHandle;
-use skia_safe::{colors, Paint};
+use skia_safe::{colors, Color, Paint};
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{EventLoop, EventLoopBuilder},
@@ -17,8 +16,9 @@ use winit::{
 };
 
 use crate::{
+    error::RunError,
     skia::SkiaGlRenderer,
-    window::{GlWindow, GlWindowManager},
+    window::{GlWindow, GlWindowManager, RendererOptions},
 };
 
 pub struct SingleWindowApplication {
@@ -134,7 +134,8 @@ impl SingleWindowApplication {
                             window,
                             &self.gl_config,
                             self.not_current_gl_context.take().unwrap(),
-                        );
+                        )
+                        .unwrap();
 
                         // Make it current.
                         // let gl_context = self
@@ -152,16 +153,13 @@ impl SingleWindowApplication {
                                 &self.gl_config,
                                 &self.gl_display,
                                 gl_window.window.inner_size(),
+                                &RendererOptions::default(),
                             )
+                            .expect("failed to create renderer")
                         });
 
                         // Try setting vsync.
-                        if let Err(res) = gl_window.surface.set_swap_interval(
-                            &gl_window.gl_context(),
-                            SwapInterval::Wait(NonZeroU32::new(1).unwrap()),
-                        ) {
-                            eprintln!("Error setting vsync: {:?}", res);
-                        }
+                        gl_window.set_vsync(true);
 
                         assert!(self.state.replace(gl_window).is_none());
                     }
@@ -191,7 +189,7 @@ impl SingleWindowApplication {
                                         NonZeroU32::new(size.height).unwrap(),
                                     );
                                     let renderer = self.renderer.as_mut().unwrap();
-                                    renderer.resize(&self.gl_config, size);
+                                    renderer.resize(size).expect("failed to resize renderer");
                                 }
                             }
                         }
@@ -203,7 +201,7 @@ impl SingleWindowApplication {
                     Event::RedrawRequested(_) => {
                         if let Some(gl_window) = &self.state {
                             let renderer = self.renderer.as_mut().unwrap();
-                            renderer.draw(|canvas| {
+                            renderer.draw(|canvas, _| {
                                 canvas.draw_circle(
                                     (200, 200),
                                     50.,
@@ -225,12 +223,19 @@ pub struct MultiWindowApplication {
     event_loop: Option<EventLoop<()>>,
 }
 impl MultiWindowApplication {
-    pub fn new() -> Self {
-        let event_loop = EventLoopBuilder::new().build();
-        Self {
-            window_manager: GlWindowManager::new(&event_loop),
+    pub fn new(options: RendererOptions) -> Result<Self, RunError> {
+        Self::with_event_loop(EventLoopBuilder::new().build(), options)
+    }
+    // For platforms where the event loop has to be configured by the caller, e.g.
+    // through the platform specific EventLoopBuilder extensions.
+    pub fn with_event_loop(
+        event_loop: EventLoop<()>,
+        options: RendererOptions,
+    ) -> Result<Self, RunError> {
+        Ok(Self {
+            window_manager: GlWindowManager::new(&event_loop, options)?,
             event_loop: Some(event_loop),
-        }
+        })
     }
     pub fn run(mut self) -> ! {
         self.event_loop
@@ -240,11 +245,21 @@ impl MultiWindowApplication {
                 control_flow.set_wait();
                 match event {
                     Event::Resumed => {
-                        self.window_manager.create_window(window_target);
+                        if let Err(err) = self.window_manager.resume(window_target) {
+                            eprintln!("Error creating window: {}", err);
+                            control_flow.set_exit();
+                        }
+                    }
+                    Event::Suspended => {
+                        self.window_manager.suspend();
                     }
 
                     Event::WindowEvent { window_id, event } => match event {
-                        WindowEvent::Resized(size) => self.window_manager.resize(&window_id, size),
+                        WindowEvent::Resized(size) => {
+                            if let Err(err) = self.window_manager.resize(&window_id, size) {
+                                eprintln!("Error resizing window: {}", err);
+                            }
+                        }
                         WindowEvent::KeyboardInput {
                             device_id,
                             input:
@@ -255,7 +270,40 @@ impl MultiWindowApplication {
                                 },
                             is_synthetic,
                         } => {
-                            self.window_manager.create_window(window_target);
+                            if let Err(err) = self.window_manager.create_window(window_target) {
+                                eprintln!("Error creating window: {}", err);
+                            }
+                        }
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::C),
+                                    state: ElementState::Released,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            let is_transparent = self.window_manager.clear_color(&window_id)
+                                == Some(Color::TRANSPARENT);
+                            let color = if is_transparent {
+                                Color::WHITE
+                            } else {
+                                Color::TRANSPARENT
+                            };
+                            self.window_manager.set_clear_color(&window_id, color);
+                        }
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    virtual_keycode: Some(VirtualKeyCode::V),
+                                    state: ElementState::Released,
+                                    ..
+                                },
+                            ..
+                        } => {
+                            if let Some(vsync) = self.window_manager.vsync(&window_id) {
+                                self.window_manager.set_vsync(&window_id, !vsync);
+                            }
                         }
                         WindowEvent::CloseRequested => {
                             if self.window_manager.close_window(&window_id) {
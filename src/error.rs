@@ -0,0 +1,62 @@
+use std::{error::Error, fmt};
+
+use winit::error::OsError;
+
+#[derive(Debug)]
+pub enum RunError {
+    // A window could not be opened.
+    Os(OsError),
+    // The GL display, a context or a window surface could not be created.
+    Gl(glutin::error::Error),
+    // Anything else reported while setting up the display.
+    Other(Box<dyn Error>),
+    // skia couldn't create its GL context on top of the current one.
+    DirectContext,
+    // skia couldn't wrap the framebuffer, even without multisampling.
+    Surface,
+}
+impl From<OsError> for RunError {
+    fn from(err: OsError) -> Self {
+        Self::Os(err)
+    }
+}
+impl From<glutin::error::Error> for RunError {
+    fn from(err: glutin::error::Error) -> Self {
+        Self::Gl(err)
+    }
+}
+impl From<Box<dyn Error>> for RunError {
+    fn from(err: Box<dyn Error>) -> Self {
+        // glutin-winit boxes whatever went wrong, so recover the concrete errors
+        // we know about.
+        let err = match err.downcast::<OsError>() {
+            Ok(err) => return Self::Os(*err),
+            Err(err) => err,
+        };
+        match err.downcast::<glutin::error::Error>() {
+            Ok(err) => Self::Gl(*err),
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Os(err) => write!(f, "failed to create window: {}", err),
+            Self::Gl(err) => write!(f, "failed to set up GL: {}", err),
+            Self::Other(err) => write!(f, "failed to set up GL display: {}", err),
+            Self::DirectContext => write!(f, "failed to create skia GL context"),
+            Self::Surface => write!(f, "failed to create skia surface"),
+        }
+    }
+}
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Os(err) => Some(err),
+            Self::Gl(err) => Some(err),
+            Self::Other(err) => Some(err.as_ref()),
+            Self::DirectContext | Self::Surface => None,
+        }
+    }
+}
@@ -6,12 +6,16 @@ pub mod gl {
 }
 
 mod app;
+mod error;
 mod skia;
 mod window;
 
+use std::error::Error;
+
 use app::MultiWindowApplication;
+use window::RendererOptions;
 
-pub fn main() {
-    let app = MultiWindowApplication::new();
+pub fn main() -> Result<(), Box<dyn Error>> {
+    let app = MultiWindowApplication::new(RendererOptions::default())?;
     app.run();
 }
@@ -2,26 +2,38 @@ use std::ffi::CString;
 
 use glutin::{config::Config, prelude::*};
 use skia_safe::{
-    gpu::{gl::FramebufferInfo, BackendRenderTarget, SurfaceOrigin},
+    gpu::{gl::FramebufferInfo, BackendRenderTarget, ContextOptions, SurfaceOrigin},
     Canvas, Color, ColorType, Surface,
 };
 use winit::dpi::PhysicalSize;
 
-use crate::gl::{self, types::GLint, Gl};
+use crate::{
+    error::RunError,
+    gl::{self, types::GLint, Gl},
+    window::RendererOptions,
+};
 
 pub struct SkiaGlRenderer {
     gl: Gl,
     fb_info: FramebufferInfo,
+    color_type: ColorType,
+    sample_count: usize,
+    stencil_bits: usize,
+    clear_color: Color,
     surface: Surface,
     gr_context: skia_safe::gpu::DirectContext,
 }
 impl SkiaGlRenderer {
-    pub fn new<D: GlDisplay>(gl_config: &Config, gl_display: &D, size: PhysicalSize<u32>) -> Self {
+    pub fn new<D: GlDisplay>(
+        gl_config: &Config,
+        gl_display: &D,
+        size: PhysicalSize<u32>,
+        options: &RendererOptions,
+    ) -> Result<Self, RunError> {
         let gl = Gl::load_with(|symbol| {
             let symbol = CString::new(symbol).unwrap();
             gl_display.get_proc_address(symbol.as_c_str()).cast()
         });
-        let mut gr_context = skia_safe::gpu::DirectContext::new_gl(None, None).unwrap();
 
         let fb_info = {
             let mut fboid: GLint = 0;
@@ -32,74 +44,183 @@ impl SkiaGlRenderer {
                 format: skia_safe::gpu::gl::Format::RGBA8.into(),
             }
         };
-        let surface = create_skia_surface(gl_config, size, &fb_info, &mut gr_context);
 
-        Self {
+        Self::with_framebuffer_info(
+            gl,
+            fb_info,
+            ColorType::RGBA8888,
+            gl_config.num_samples().into(),
+            gl_config.stencil_size().into(),
+            size,
+            options,
+        )
+    }
+    // Lower-level constructor that renders into the given framebuffer instead of
+    // whichever one is bound when the renderer is created, e.g. an FBO backed by a
+    // texture that the caller composites with its own GL rendering. The color
+    // type, sample count and stencil bits must describe that framebuffer, not the
+    // window's config.
+    pub fn with_framebuffer_info(
+        gl: Gl,
+        fb_info: FramebufferInfo,
+        color_type: ColorType,
+        sample_count: usize,
+        stencil_bits: usize,
+        size: PhysicalSize<u32>,
+        options: &RendererOptions,
+    ) -> Result<Self, RunError> {
+        let mut context_options = ContextOptions::default();
+        context_options.glyph_cache_texture_maximum_bytes =
+            options.glyph_cache_texture_maximum_bytes;
+        let mut gr_context = skia_safe::gpu::DirectContext::new_gl(None, &context_options)
+            .ok_or(RunError::DirectContext)?;
+        gr_context.set_resource_cache_limit(options.resource_cache_limit);
+
+        let (surface, sample_count) = create_skia_surface(
+            size,
+            &fb_info,
+            color_type,
+            sample_count,
+            stencil_bits,
+            &mut gr_context,
+        )?;
+
+        Ok(Self {
             gl,
             fb_info,
+            color_type,
+            sample_count,
+            stencil_bits,
+            clear_color: options.clear_color,
             surface,
             gr_context,
-        }
+        })
     }
-    pub fn resize(
-        &mut self,
-        gl_config: &Config,
-        size: PhysicalSize<u32>,
-    ) {
-        self.resize_viewport(
-            size.width.try_into().unwrap(),
-            size.height.try_into().unwrap(),
-        );
-        self.create_surface(gl_config, size);
+    pub fn resize(&mut self, size: PhysicalSize<u32>) -> Result<(), RunError> {
+        // The viewport is context state, so leave it alone for a caller-owned
+        // framebuffer that is composited with the caller's own GL rendering.
+        if self.fb_info.fboid == 0 {
+            self.resize_viewport(
+                size.width.try_into().unwrap(),
+                size.height.try_into().unwrap(),
+            );
+        }
+        self.create_surface(size)
     }
     fn resize_viewport(&self, width: i32, height: i32) {
         unsafe {
             self.gl.Viewport(0, 0, width, height);
         }
     }
-    fn create_surface(
-        &mut self,
-        gl_config: &Config,
-        size: PhysicalSize<u32>,
-    ) {
-        self.surface = create_skia_surface(
-            gl_config,
+    fn create_surface(&mut self, size: PhysicalSize<u32>) -> Result<(), RunError> {
+        let (surface, sample_count) = create_skia_surface(
             size,
             &self.fb_info,
+            self.color_type,
+            self.sample_count,
+            self.stencil_bits,
             &mut self.gr_context,
-        );
+        )?;
+        // Remember what skia accepted so later resizes don't retry (and report)
+        // the same fallback again.
+        self.surface = surface;
+        self.sample_count = sample_count;
+        Ok(())
     }
-    pub fn draw(&mut self, paint: impl FnOnce(&mut Canvas)) {
-        {
-            let canvas = self.surface.canvas();
-            canvas.clear(Color::TRANSPARENT);
-            paint(canvas);
-        }
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = color;
+    }
+    // The painter also gets the window's DirectContext, so it can create GPU
+    // backed images and surfaces that live alongside this surface across frames.
+    pub fn draw(&mut self, paint: impl FnOnce(&mut Canvas, &mut skia_safe::gpu::DirectContext)) {
+        let gr_context = &mut self.gr_context;
+        paint_frame(self.surface.canvas(), self.clear_color, |canvas| {
+            paint(canvas, gr_context)
+        });
         self.gr_context.flush(None);
     }
 }
+fn paint_frame(canvas: &mut Canvas, clear_color: Color, paint: impl FnOnce(&mut Canvas)) {
+    canvas.clear(clear_color);
+    // The surface (and its canvas) outlives the frame, so undo any save() the
+    // painter forgot to restore before the next frame.
+    let save_count = canvas.save_count();
+    paint(canvas);
+    canvas.restore_to_count(save_count);
+}
 fn create_skia_surface(
-    gl_config: &Config,
     size: PhysicalSize<u32>,
     fb_info: &FramebufferInfo,
+    color_type: ColorType,
+    sample_count: usize,
+    stencil_bits: usize,
     gr_context: &mut skia_safe::gpu::DirectContext,
-) -> skia_safe::Surface {
-    let backend_render_target = BackendRenderTarget::new_gl(
-        (
-            size.width.try_into().unwrap(),
-            size.height.try_into().unwrap(),
-        ),
-        Some(gl_config.num_samples().into()),
-        gl_config.stencil_size().into(),
-        *fb_info,
-    );
-    Surface::from_backend_render_target(
-        gr_context,
-        &backend_render_target,
-        SurfaceOrigin::BottomLeft,
-        ColorType::RGBA8888,
-        None,
-        None,
-    )
-    .unwrap()
+) -> Result<(skia_safe::Surface, usize), RunError> {
+    // Some drivers advertise configs whose sample/stencil combination skia can't
+    // wrap, so keep halving the sample count until skia accepts the target.
+    let mut num_samples = sample_count;
+    loop {
+        let backend_render_target = BackendRenderTarget::new_gl(
+            (
+                size.width.try_into().unwrap(),
+                size.height.try_into().unwrap(),
+            ),
+            Some(num_samples),
+            stencil_bits,
+            *fb_info,
+        );
+        if let Some(surface) = Surface::from_backend_render_target(
+            gr_context,
+            &backend_render_target,
+            SurfaceOrigin::BottomLeft,
+            color_type,
+            None,
+            None,
+        ) {
+            if num_samples != sample_count {
+                eprintln!(
+                    "Framebuffer has {} samples, fell back to {} for the skia surface",
+                    sample_count, num_samples
+                );
+            }
+            return Ok((surface, num_samples));
+        }
+
+        if num_samples == 0 {
+            return Err(RunError::Surface);
+        }
+        num_samples /= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use skia_safe::{Canvas, Color, IRect, Rect, Surface};
+
+    use super::paint_frame;
+
+    fn assert_clean(canvas: &Canvas) {
+        assert_eq!(canvas.save_count(), 1);
+        assert!(canvas.local_to_device_as_3x3().is_identity());
+        assert_eq!(canvas.device_clip_bounds(), Some(IRect::from_wh(64, 64)));
+    }
+
+    #[test]
+    fn unbalanced_save_does_not_leak_into_next_frame() {
+        let mut surface = Surface::new_raster_n32_premul((64, 64)).unwrap();
+        let canvas = surface.canvas();
+
+        paint_frame(canvas, Color::TRANSPARENT, |canvas| {
+            canvas.save();
+            canvas.translate((10., 10.));
+            canvas.clip_rect(Rect::from_wh(8., 8.), None, None);
+        });
+        assert_clean(canvas);
+
+        paint_frame(canvas, Color::TRANSPARENT, |canvas| assert_clean(canvas));
+        assert_clean(canvas);
+    }
 }
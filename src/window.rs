@@ -1,4 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, num::NonZeroU32, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    num::NonZeroU32,
+};
 
 use glutin::{
     config::{Config, ConfigTemplateBuilder},
@@ -9,46 +13,49 @@ use glutin::{
 };
 use glutin_winit::DisplayBuilder;
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
-use skia_safe::{colors, Paint};
+use skia_safe::{colors, Color, Paint};
 use winit::{
     dpi::PhysicalSize,
     event_loop::EventLoopWindowTarget,
     window::{Window, WindowBuilder, WindowId},
 };
 
-use crate::skia::SkiaGlRenderer;
+use crate::{error::RunError, skia::SkiaGlRenderer};
 
 pub struct GlWindow {
     gl_context: Option<PossiblyCurrentContext>,
+    vsync: Cell<bool>,
     // XXX the surface must be dropped before the window.
     pub surface: Surface<WindowSurface>,
     pub window: Window,
 }
 
 impl GlWindow {
-    pub fn new(window: Window, config: &Config, not_current_gl_context: NotCurrentContext) -> Self {
+    pub fn new(
+        window: Window,
+        config: &Config,
+        not_current_gl_context: NotCurrentContext,
+    ) -> Result<Self, RunError> {
         let (width, height): (u32, u32) = window.inner_size().into();
         let raw_window_handle = window.raw_window_handle();
+        // A window can start out minimized. The surface is resized on the first
+        // Resized event, so a 1x1 placeholder is enough until then.
         let attrs = SurfaceAttributesBuilder::<WindowSurface>::new().build(
             raw_window_handle,
-            NonZeroU32::new(width).unwrap(),
-            NonZeroU32::new(height).unwrap(),
+            NonZeroU32::new(width).unwrap_or(NonZeroU32::MIN),
+            NonZeroU32::new(height).unwrap_or(NonZeroU32::MIN),
         );
 
-        let surface = unsafe {
-            config
-                .display()
-                .create_window_surface(config, &attrs)
-                .unwrap()
-        };
+        let surface = unsafe { config.display().create_window_surface(config, &attrs)? };
         // Make it current.
-        let gl_context = not_current_gl_context.make_current(&surface).unwrap();
+        let gl_context = not_current_gl_context.make_current(&surface)?;
 
-        Self {
+        Ok(Self {
             window,
             surface,
             gl_context: Some(gl_context),
-        }
+            vsync: Cell::new(false),
+        })
     }
     pub fn gl_context(&self) -> &PossiblyCurrentContext {
         self.gl_context.as_ref().unwrap()
@@ -62,7 +69,7 @@ impl GlWindow {
             gl_context.make_current(&self.surface).unwrap();
         }
     }
-    pub fn resize(&self, width: NonZeroU32, height: NonZeroU32) {
+    pub fn resize(&self, width: NonZeroU32, height: NonZeroU32) -> PhysicalSize<u32> {
         self.make_current_if_needed();
         let gl_context = self.gl_context();
         // Some platforms like EGL require resizing GL surface to update the size
@@ -70,32 +77,116 @@ impl GlWindow {
         // and the function is no-op, but it's wise to resize it for portability
         // reasons.
         self.surface.resize(gl_context, width, height);
+
+        self.surface_size(PhysicalSize::new(width.get(), height.get()))
+    }
+    pub fn size(&self) -> PhysicalSize<u32> {
+        self.surface_size(self.window.inner_size())
+    }
+    fn surface_size(&self, fallback: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        // The backing size can lag behind or be rounded differently from the
+        // window's (e.g. on HiDPI compositors), so report what the surface has.
+        surface_size_or(self.surface.width(), self.surface.height(), fallback)
+    }
+    pub fn vsync(&self) -> bool {
+        self.vsync.get()
+    }
+    pub fn set_vsync(&self, enabled: bool) {
+        // The swap interval applies to whichever surface is current on EGL.
+        self.make_current_if_needed();
+        let interval = if enabled {
+            SwapInterval::Wait(NonZeroU32::new(1).unwrap())
+        } else {
+            SwapInterval::DontWait
+        };
+        match self.surface.set_swap_interval(self.gl_context(), interval) {
+            Ok(()) => self.vsync.set(enabled),
+            Err(res) => eprintln!("Error setting vsync: {:?}", res),
+        }
     }
     pub fn swap_buffers(&self) {
         self.surface.swap_buffers(&self.gl_context()).unwrap();
     }
 }
+fn surface_size_or(
+    width: Option<u32>,
+    height: Option<u32>,
+    fallback: PhysicalSize<u32>,
+) -> PhysicalSize<u32> {
+    // EGL reports 0 when the query fails, so treat that as unknown.
+    PhysicalSize::new(
+        width.filter(|&width| width != 0).unwrap_or(fallback.width),
+        height
+            .filter(|&height| height != 0)
+            .unwrap_or(fallback.height),
+    )
+}
 impl Drop for GlWindow {
     fn drop(&mut self) {
-        self.gl_context.take().unwrap().make_not_current().unwrap();
+        // The context is already gone if it was handed back by `make_not_current`.
+        if let Some(gl_context) = self.gl_context.take() {
+            gl_context.make_not_current().unwrap();
+        }
+    }
+}
+
+// Skia defaults to an 8MiB glyph atlas, which text-heavy desktop windows can
+// outgrow and end up re-rasterizing glyphs. The resource cache keeps skia's
+// 256MiB default, since every window pays for it separately.
+const GLYPH_CACHE_TEXTURE_MAXIMUM_BYTES: usize = 2048 * 2048 * 4;
+const RESOURCE_CACHE_LIMIT: usize = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RendererOptions {
+    // The MSAA sample count to ask for. When no config has exactly this many
+    // samples the closest one is picked instead. `None` picks the config with the
+    // most samples available.
+    pub msaa_samples: Option<u8>,
+    // Every window has its own DirectContext, so both limits apply per window
+    // rather than being shared across the application.
+    pub glyph_cache_texture_maximum_bytes: usize,
+    pub resource_cache_limit: usize,
+    // What every frame, including the one presented before a window is shown, is
+    // cleared to. This defaults to transparent to match the transparent windows.
+    // Opaque apps should pick an opaque color, which also keeps the first frame
+    // from showing through and skips blending with whatever is behind the window.
+    pub clear_color: Color,
+}
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            msaa_samples: None,
+            glyph_cache_texture_maximum_bytes: GLYPH_CACHE_TEXTURE_MAXIMUM_BYTES,
+            resource_cache_limit: RESOURCE_CACHE_LIMIT,
+            clear_color: Color::TRANSPARENT,
+        }
     }
 }
 
 pub struct GlWindowManager {
     gl_config: Config,
     gl_display: Display,
+    options: RendererOptions,
     first_window: Option<Window>,
-    windows: HashMap<WindowId, Rc<SkiaGlAppWindow>>,
+    windows: HashMap<WindowId, SkiaGlAppWindow>,
+    suspended_windows: Vec<SuspendedWindow>,
 }
 impl GlWindowManager {
-    pub fn new(window_target: &EventLoopWindowTarget<()>) -> Self {
+    pub fn new(
+        window_target: &EventLoopWindowTarget<()>,
+        options: RendererOptions,
+    ) -> Result<Self, RunError> {
         // Only windows requires the window to be present before creating the display.
         // Other platforms don't really need one.
         //
         // XXX if you don't care about running on android or so you can safely remove
         // this condition and always pass the window builder.
         let window_builder = if cfg!(wgl_backend) {
-            Some(WindowBuilder::new().with_transparent(true))
+            Some(
+                WindowBuilder::new()
+                    .with_transparent(true)
+                    .with_visible(false),
+            )
         } else {
             None
         };
@@ -106,24 +197,38 @@ impl GlWindowManager {
 
         let display_builder = DisplayBuilder::new().with_window_builder(window_builder);
 
-        let (first_window, gl_config) = display_builder
-            .build(&window_target, template, |configs| {
-                // Find the config with the maximum number of samples, so our triangle will
-                // be smooth.
+        let (first_window, gl_config) =
+            display_builder.build(&window_target, template, |configs| {
+                // Find the config with the requested number of samples, or the maximum
+                // number of samples if none was requested, so our triangle will be smooth.
                 configs
                     .reduce(|accum, config| {
-                        let transparency_check = config.supports_transparency().unwrap_or(false)
-                            & !accum.supports_transparency().unwrap_or(false);
+                        // The windows are transparent, so never trade transparency for samples.
+                        let config_transparent = config.supports_transparency().unwrap_or(false);
+                        let accum_transparent = accum.supports_transparency().unwrap_or(false);
+                        if config_transparent != accum_transparent {
+                            return if config_transparent { config } else { accum };
+                        }
+
+                        let samples_check = match options.msaa_samples {
+                            Some(samples) => {
+                                samples.abs_diff(config.num_samples())
+                                    < samples.abs_diff(accum.num_samples())
+                            }
+                            None => config.num_samples() > accum.num_samples(),
+                        };
 
-                        if transparency_check || config.num_samples() > accum.num_samples() {
+                        if samples_check {
                             config
                         } else {
                             accum
                         }
                     })
-                    .unwrap()
-            })
-            .unwrap();
+                    // glutin-winit 0.2's picker has to return a Config, so it can't report
+                    // that glutin's filters rejected every config. That case still panics
+                    // here instead of becoming a RunError.
+                    .expect("no GL config supports rendering to a window")
+            })?;
 
         println!("Picked a config with {} samples", gl_config.num_samples());
 
@@ -131,14 +236,19 @@ impl GlWindowManager {
         // can query it from the config.
         let gl_display = gl_config.display();
 
-        Self {
+        Ok(Self {
             gl_config,
             gl_display,
+            options,
             first_window,
             windows: HashMap::new(),
-        }
+            suspended_windows: Vec::new(),
+        })
     }
-    fn create_context(&self, raw_window_handle: RawWindowHandle) -> NotCurrentContext {
+    fn create_context(
+        &self,
+        raw_window_handle: RawWindowHandle,
+    ) -> Result<NotCurrentContext, RunError> {
         // The context creation part. It can be created before surface and that's how
         // it's expected in multithreaded + multiwindow operation mode, since you
         // can send NotCurrentContext, but not Surface.
@@ -149,35 +259,34 @@ impl GlWindowManager {
         let fallback_context_attributes = ContextAttributesBuilder::new()
             .with_context_api(ContextApi::Gles(None))
             .build(Some(raw_window_handle));
-        unsafe {
+        let gl_context = unsafe {
             self.gl_display
                 .create_context(&self.gl_config, &context_attributes)
-                .unwrap_or_else(|_| {
+                .or_else(|_| {
                     self.gl_display
                         .create_context(&self.gl_config, &fallback_context_attributes)
-                        .expect("failed to create context")
-                })
-        }
+                })?
+        };
+        Ok(gl_context)
     }
     pub fn close_window(&mut self, id: &WindowId) -> bool {
-        self.windows.remove(&id);
+        if let Some(window) = self.windows.remove(id) {
+            window.tear_down();
+        }
         self.windows.is_empty()
     }
     pub fn create_window(
         &mut self,
         window_target: &EventLoopWindowTarget<()>,
-    ) -> Rc<SkiaGlAppWindow> {
+    ) -> Result<WindowId, RunError> {
         #[cfg(target_os = "android")]
         println!("Android window available");
 
-        let window = self.first_window.take().unwrap_or_else(|| {
-            let window_builder = WindowBuilder::new().with_transparent(true);
-            glutin_winit::finalize_window(window_target, window_builder, &self.gl_config).unwrap()
-        });
+        let window = self.build_window(window_target)?;
 
-        let not_current_gl_context = self.create_context(window.raw_window_handle());
+        let not_current_gl_context = self.create_context(window.raw_window_handle())?;
 
-        let gl_window = GlWindow::new(window, &self.gl_config, not_current_gl_context);
+        let gl_window = GlWindow::new(window, &self.gl_config, not_current_gl_context)?;
 
         // The context needs to be current for the Renderer to set up shaders and
         // buffers. It also performs function loading, which needs a current context on
@@ -185,56 +294,218 @@ impl GlWindowManager {
         let renderer = SkiaGlRenderer::new(
             &self.gl_config,
             &self.gl_display,
-            gl_window.window.inner_size(),
-        );
+            gl_window.size(),
+            &self.options,
+        )?;
+
+        Ok(self.insert_window(renderer, gl_window, true))
+    }
+    pub fn resume(&mut self, window_target: &EventLoopWindowTarget<()>) -> Result<(), RunError> {
+        if self.suspended_windows.is_empty() {
+            self.create_window(window_target)?;
+            return Ok(());
+        }
+
+        #[cfg(target_os = "android")]
+        println!("Android window available");
+
+        // The native windows are gone, but the contexts survived the suspension, so
+        // new windows and surfaces are created for them. Skia was torn down with the
+        // surfaces and is set up again once the context is current.
+        for suspended in std::mem::take(&mut self.suspended_windows) {
+            let window = self.build_window(window_target)?;
+
+            let gl_window =
+                GlWindow::new(window, &self.gl_config, suspended.not_current_gl_context)?;
 
-        // Try setting vsync.
-        if let Err(res) = gl_window.surface.set_swap_interval(
-            gl_window.gl_context(),
-            SwapInterval::Wait(NonZeroU32::new(1).unwrap()),
-        ) {
-            eprintln!("Error setting vsync: {:?}", res);
+            let mut renderer = SkiaGlRenderer::new(
+                &self.gl_config,
+                &self.gl_display,
+                gl_window.size(),
+                &self.options,
+            )?;
+            renderer.set_clear_color(suspended.clear_color);
+
+            self.insert_window(renderer, gl_window, suspended.vsync);
+        }
+        Ok(())
+    }
+    pub fn suspend(&mut self) {
+        // This is only raised on Android, where the backing NativeWindow for a GL
+        // Surface can appear and disappear at any moment.
+        println!("Android window removed");
+
+        // Destroy the GL Surfaces and un-current the GL Contexts before ndk-glue
+        // releases the windows back to the system.
+        for (_, window) in self.windows.drain() {
+            self.suspended_windows.push(window.tear_down());
+        }
+    }
+    fn build_window(
+        &mut self,
+        window_target: &EventLoopWindowTarget<()>,
+    ) -> Result<Window, RunError> {
+        if let Some(window) = self.first_window.take() {
+            return Ok(window);
         }
+        let window_builder = WindowBuilder::new()
+            .with_transparent(true)
+            .with_visible(false);
+        let window = glutin_winit::finalize_window(window_target, window_builder, &self.gl_config)?;
+        Ok(window)
+    }
+    fn insert_window(
+        &mut self,
+        mut renderer: SkiaGlRenderer,
+        gl_window: GlWindow,
+        vsync: bool,
+    ) -> WindowId {
+        // Windows are created hidden so that the compositor never shows an
+        // undefined framebuffer. Present one cleared frame before revealing it. It
+        // uses the same clear color as every later frame: the windows are
+        // transparent, so an opaque first frame would flash before the content.
+        // Apps that want an opaque first frame set an opaque
+        // `RendererOptions::clear_color`.
+        renderer.draw(|_, _| {});
+        gl_window.swap_buffers();
+        gl_window.window.set_visible(true);
 
-        let window = Rc::new(SkiaGlAppWindow {
+        // Only enable vsync once the window is shown. Platforms that throttle
+        // occluded windows (e.g. macOS) can block a vsync'd swap on a hidden one.
+        gl_window.set_vsync(vsync);
+
+        let id = gl_window.window.id();
+        let window = SkiaGlAppWindow {
             renderer: RefCell::new(renderer),
             gl_window,
-        });
-        let id = window.gl_window.window.id();
-        self.windows.insert(id, window.clone());
-        window
+        };
+        self.windows.insert(id, window);
+        id
     }
-    pub fn resize(&self, id: &WindowId, size: PhysicalSize<u32>) {
+    pub fn resize(&self, id: &WindowId, size: PhysicalSize<u32>) -> Result<(), RunError> {
+        // Events for windows torn down by a suspension can still be in flight.
+        let Some(window) = self.windows.get(id) else {
+            return Ok(());
+        };
         if size.width != 0 && size.height != 0 {
-            let window = self.windows.get(id).unwrap();
-            window.resize(&self.gl_config, size);
+            window.resize(size)?;
+            // During an interactive resize on Windows and macOS the event loop is
+            // pumped from inside the OS modal loop and RedrawRequested isn't
+            // delivered until the user lets go, so draw right away to keep the
+            // content live.
+            window.draw();
         }
+        Ok(())
     }
     pub fn draw(&self, id: &WindowId) {
-        let window = self.windows.get(id).unwrap();
-        window.draw();
+        if let Some(window) = self.windows.get(id) {
+            window.draw();
+        }
+    }
+    pub fn clear_color(&self, id: &WindowId) -> Option<Color> {
+        let window = self.windows.get(id)?;
+        let clear_color = window.renderer.borrow().clear_color();
+        Some(clear_color)
+    }
+    pub fn vsync(&self, id: &WindowId) -> Option<bool> {
+        Some(self.windows.get(id)?.gl_window.vsync())
+    }
+    pub fn set_vsync(&self, id: &WindowId, enabled: bool) {
+        if let Some(window) = self.windows.get(id) {
+            window.gl_window.set_vsync(enabled);
+        }
+    }
+    pub fn set_clear_color(&self, id: &WindowId, color: Color) {
+        if let Some(window) = self.windows.get(id) {
+            window.renderer.borrow_mut().set_clear_color(color);
+            window.gl_window.window.request_redraw();
+        }
     }
 }
 
-pub struct SkiaGlAppWindow {
+struct SuspendedWindow {
+    clear_color: Color,
+    vsync: bool,
+    not_current_gl_context: NotCurrentContext,
+}
+
+struct SkiaGlAppWindow {
     renderer: RefCell<SkiaGlRenderer>,
     gl_window: GlWindow,
 }
 impl SkiaGlAppWindow {
-    fn resize(&self, gl_config: &Config, size: PhysicalSize<u32>) {
-        self.gl_window.resize(
+    fn tear_down(self) -> SuspendedWindow {
+        let Self {
+            renderer,
+            gl_window,
+        } = self;
+        // Skia frees its GL resources when the DirectContext is dropped, which
+        // needs this window's context to be current rather than whichever window
+        // drew last.
+        gl_window.make_current_if_needed();
+        let renderer = renderer.into_inner();
+        let clear_color = renderer.clear_color();
+        drop(renderer);
+
+        SuspendedWindow {
+            clear_color,
+            vsync: gl_window.vsync(),
+            not_current_gl_context: gl_window.make_not_current(),
+        }
+    }
+    fn resize(&self, size: PhysicalSize<u32>) -> Result<(), RunError> {
+        let surface_size = self.gl_window.resize(
             NonZeroU32::new(size.width).unwrap(),
             NonZeroU32::new(size.height).unwrap(),
         );
         let mut renderer = self.renderer.borrow_mut();
-        renderer.resize(&gl_config, size);
+        renderer.resize(surface_size)
     }
     fn draw(&self) {
+        // winit buffers events raised while a handler is running instead of
+        // re-entering the loop, so this can't overlap with a resize.
+        let mut renderer = self.renderer.borrow_mut();
         self.gl_window.make_current_if_needed();
-        self.renderer.borrow_mut().draw(|canvas| {
+        renderer.draw(|canvas, _| {
             canvas.draw_circle((200, 200), 50., &Paint::new(colors::CYAN, None));
         });
 
         self.gl_window.swap_buffers();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalSize;
+
+    use super::surface_size_or;
+
+    #[test]
+    fn surface_size_prefers_known_dimensions() {
+        let fallback = PhysicalSize::new(800, 600);
+        assert_eq!(
+            surface_size_or(Some(1600), Some(1200), fallback),
+            PhysicalSize::new(1600, 1200)
+        );
+    }
+
+    #[test]
+    fn surface_size_treats_zero_as_unknown() {
+        let fallback = PhysicalSize::new(800, 600);
+        assert_eq!(surface_size_or(Some(0), Some(0), fallback), fallback);
+        assert_eq!(
+            surface_size_or(Some(1600), Some(0), fallback),
+            PhysicalSize::new(1600, 600)
+        );
+    }
+
+    #[test]
+    fn surface_size_falls_back_when_unavailable() {
+        let fallback = PhysicalSize::new(800, 600);
+        assert_eq!(surface_size_or(None, None, fallback), fallback);
+        assert_eq!(
+            surface_size_or(None, Some(1200), fallback),
+            PhysicalSize::new(800, 1200)
+        );
+    }
+}